@@ -1,41 +1,41 @@
 //! The hkdf module provides access to the functionality provoided HKDF as
 //! specified in [RFC5869](https://tools.ietf.org/html/rfc5869)
 //!
-//! A wrapper around the rust-crypto implementation of HKDF
-//! [RFC5869](https://tools.ietf.org/html/rfc5869) locked to using SHA512 as the
-//! underlying hash function. Focuses on splitting the `extract` and `expand`
+//! Implements HKDF [RFC5869](https://tools.ietf.org/html/rfc5869) directly on
+//! top of the maintained `hmac`/`sha2` crates, generic over the underlying
+//! hash function. Focuses on splitting the `extract` and `expand`
 //! functionality.
 //!
-//! TODO: Rewrite to use the ring implementation. There were some difficulties
-//! around the way that ring does not give access to the raw bytes output by
-//! these algorithms
+//! SHA-512 remains the default, backwards-compatible instantiation (see
+//! [`Hkdf`]/[`HkdfSha512`]); [`HkdfSha256`] is also provided, and any hash
+//! implemented by the `digest` crate can be used via `Hkdf<H>`.
 ///
 /// # Examples
 ///
 /// Run using specific instantiation:
 /// ```
-/// use hkdf_sha512::Hkdf;
+/// use hkdf_sha512::{Hkdf,Salt};
 /// use rand::rngs::OsRng;
 /// use rand_core::RngCore;
 ///
-/// let hkdf = Hkdf{};
+/// let hkdf = Hkdf::new();
 ///
 /// // extract bytes from random seed
 /// let mut rng = OsRng;
 /// let mut seed = vec![0; 32]; // length of seed determines security
 /// rng.fill_bytes(&mut seed);
 /// let mut out = Vec::new(); // output buffer will be resized by extract
-/// hkdf.extract(&seed, "some_secret_info".as_bytes(), &mut out); // out corresponds to raw PRK
+/// hkdf.extract(Salt::NonEmpty(&seed), "some_secret_info".as_bytes(), &mut out); // out corresponds to raw PRK
 ///
 /// // expand into output using raw PRK
 /// let expand_len = 70; // length of output buffer required
 /// let mut exp_out = vec![0; expand_len];
-/// hkdf.expand(&out, "some_info".as_bytes(), &mut exp_out);
+/// hkdf.expand(&out, "some_info".as_bytes(), &mut exp_out).unwrap();
 /// ```
 ///
 /// Can also run using methods directly:
 /// ```
-/// use hkdf_sha512::{extract,expand};
+/// use hkdf_sha512::{extract,expand,Salt};
 /// use rand::rngs::OsRng;
 /// use rand_core::RngCore;
 ///
@@ -44,51 +44,369 @@
 /// let mut seed = vec![0; 32]; // length of seed determines security
 /// rng.fill_bytes(&mut seed);
 /// let mut out = Vec::new(); // output buffer will be resized by extract
-/// extract(&seed, "some_secret_info".as_bytes(), &mut out); // out corresponds to raw PRK
+/// extract(Salt::NonEmpty(&seed), "some_secret_info".as_bytes(), &mut out); // out corresponds to raw PRK
 ///
 /// // expand into output using raw PRK
 /// let expand_len = 70; // length of output buffer required
 /// let mut exp_out = vec![0; expand_len];
-/// expand(&out, "some_info".as_bytes(), &mut exp_out);
+/// expand(&out, "some_info".as_bytes(), &mut exp_out).unwrap();
 /// ```
+///
+/// Or with a different hash function:
+/// ```
+/// use hkdf_sha512::{HkdfSha256,Salt};
+///
+/// let hkdf = HkdfSha256::default();
+/// let mut prk = Vec::new();
+/// hkdf.extract(Salt::None, "some_secret_info".as_bytes(), &mut prk);
+///
+/// let mut out = vec![0; 32];
+/// hkdf.expand(&prk, "some_info".as_bytes(), &mut out).unwrap();
+/// ```
+
+use std::borrow::Cow;
+use std::fmt;
+use std::marker::PhantomData;
+
+use hmac::digest::core_api::BlockSizeUser;
+use hmac::{Mac,SimpleHmac};
+use sha2::{Digest,Sha256,Sha512};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// Error returned when the output requested from `expand` is longer than
+/// `255 * HashLen` bytes, as disallowed by
+/// [RFC5869](https://tools.ietf.org/html/rfc5869#section-2.3).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidLength;
+
+impl fmt::Display for InvalidLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid number of blocks, too large output")
+    }
+}
+
+impl std::error::Error for InvalidLength {}
+
+/// Error returned when a `prk` passed to `expand` is shorter than `HashLen`
+/// bytes, and therefore cannot have been produced by `extract`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidPrkLength;
+
+impl fmt::Display for InvalidPrkLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid pseudorandom key length, too short")
+    }
+}
+
+impl std::error::Error for InvalidPrkLength {}
+
+/// Error returned by `expand` (and everything built on it: `derive`,
+/// `expand_array`, `derive_array`) since either a too-short `prk` or a
+/// too-long requested output can fail it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ExpandError {
+    /// See `InvalidPrkLength`.
+    InvalidPrkLength(InvalidPrkLength),
+    /// See `InvalidLength`.
+    InvalidLength(InvalidLength),
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpandError::InvalidPrkLength(e) => e.fmt(f),
+            ExpandError::InvalidLength(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ExpandError {}
+
+impl From<InvalidPrkLength> for ExpandError {
+    fn from(e: InvalidPrkLength) -> Self {
+        ExpandError::InvalidPrkLength(e)
+    }
+}
+
+impl From<InvalidLength> for ExpandError {
+    fn from(e: InvalidLength) -> Self {
+        ExpandError::InvalidLength(e)
+    }
+}
+
+/// The salt used during HKDF-Extract, as specified in
+/// [RFC5869](https://tools.ietf.org/html/rfc5869#section-2.2).
+///
+/// RFC5869 treats "no salt was supplied" and "the salt is `HashLen` zero
+/// bytes" identically, but conflating that with "an empty slice means no
+/// salt" in caller code is an easy source of silent misuse. `Salt` makes the
+/// "no salt" case explicit instead.
+pub enum Salt<'a> {
+    /// No salt was supplied; a string of `HashLen` zero bytes is substituted
+    /// in its place, per RFC5869.
+    None,
+    /// An explicit, non-empty salt.
+    NonEmpty(&'a [u8]),
+}
+
+impl<'a> Salt<'a> {
+    /// Returns the bytes to key HKDF-Extract with, substituting `hash_len`
+    /// zero bytes for `Salt::None`.
+    fn as_bytes(&self, hash_len: usize) -> Cow<'a, [u8]> {
+        match self {
+            Salt::None => Cow::Owned(vec![0; hash_len]),
+            Salt::NonEmpty(salt) => Cow::Borrowed(salt),
+        }
+    }
+}
+
+/// A PRK produced by `HkdfExtract::finalize`, ready to be passed to
+/// `expand`.
+///
+/// Behaves like the underlying bytes via `Deref`/`AsRef`. When built with
+/// the `zeroize` feature enabled, the buffer is wiped when it goes out of
+/// scope so this pseudorandom key material does not linger in memory.
+/// `extract` and `Hkdf::extract` do not return a `Prk`: they copy the PRK
+/// into the caller's `Vec<u8>`, which is not wiped on drop.
+pub struct Prk(Vec<u8>);
+
+impl Prk {
+    fn new(bytes: Vec<u8>) -> Self {
+        Prk(bytes)
+    }
+}
+
+impl std::ops::Deref for Prk {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
 
-use crypto::hkdf::{hkdf_extract,hkdf_expand};
-use crypto::sha2::Sha512;
+impl AsRef<[u8]> for Prk {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
 
-const SHA512_OUTPUT_BYTES_LENGTH: usize = 64;
+#[cfg(feature = "zeroize")]
+impl Drop for Prk {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
 
-/// A wrapper around the HKDF functionality in cases where it is required that
-/// you return a specific instance of HKDF.
+/// A wrapper around the HKDF functionality, generic over the underlying hash
+/// function `H`.
 ///
-/// (largely redundant as we only support SHA-512)
-pub struct Hkdf{}
+/// Defaults to SHA-512, matching this crate's original, SHA-512-only
+/// behavior; use [`HkdfSha256`] for SHA-256, or `Hkdf<H>` directly for any
+/// other hash implemented by the `digest` crate.
+pub struct Hkdf<H = Sha512> {
+    _hash: PhantomData<H>,
+}
 
-impl Hkdf {
+/// HKDF instantiated with SHA-512 — this crate's original, default hash.
+pub type HkdfSha512 = Hkdf<Sha512>;
+
+/// HKDF instantiated with SHA-256.
+pub type HkdfSha256 = Hkdf<Sha256>;
+
+impl Hkdf<Sha512> {
+    /// Creates the default, SHA-512 instance, matching the behavior of
+    /// earlier releases of this crate, which only supported SHA-512.
+    pub fn new() -> Self {
+        Hkdf { _hash: PhantomData }
+    }
+}
+
+impl<H: Digest + Clone + BlockSizeUser> Default for Hkdf<H> {
+    fn default() -> Self {
+        Hkdf { _hash: PhantomData }
+    }
+}
+
+impl<H: Digest + Clone + BlockSizeUser> Hkdf<H> {
     /// extract for specific HKDF invocation
-    pub fn extract(&self, seed: &[u8], secret: &[u8], out: &mut Vec<u8>) {
-        extract(seed, secret, out)
+    pub fn extract(&self, salt: Salt, secret: &[u8], out: &mut Vec<u8>) {
+        let mut ctx = HkdfExtract::<H>::new(salt);
+        ctx.input_ikm(secret);
+        let (prk, _) = ctx.finalize();
+        copy_into_cleared(&prk, out);
     }
 
     /// expand for specific HKDF invocation
-    pub fn expand(&self, prk: &[u8], info: &[u8], out: &mut Vec<u8>) {
-        expand(prk, info, out)
+    ///
+    /// Returns `ExpandError::InvalidPrkLength` if `prk` is shorter than
+    /// `HashLen` bytes, and `ExpandError::InvalidLength` if `out` requests
+    /// more than `255 * HashLen` bytes, as required by RFC5869.
+    pub fn expand(&self, prk: &[u8], info: &[u8], out: &mut Vec<u8>) -> Result<(), ExpandError> {
+        let hash_len = <H as Digest>::output_size();
+        check_prk_length::<H>(prk)?;
+        if out.len() > 255 * hash_len {
+            return Err(InvalidLength.into());
+        }
+
+        let mut t: Vec<u8> = Vec::new();
+        let mut counter: u8 = 0;
+        let mut filled = 0;
+        while filled < out.len() {
+            counter += 1;
+            // `SimpleHmac` doesn't implement `FixedOutputReset`, so rather
+            // than resetting one context we key a fresh one for each T(i).
+            let mut mac = <SimpleHmac<H> as Mac>::new_from_slice(prk)
+                .expect("HMAC accepts a key of any length");
+            mac.update(&t);
+            mac.update(info);
+            mac.update(&[counter]);
+            let block = mac.finalize().into_bytes();
+            t.clear();
+            t.extend_from_slice(&block);
+
+            let take = std::cmp::min(t.len(), out.len() - filled);
+            out[filled..filled + take].copy_from_slice(&t[..take]);
+            filled += take;
+        }
+        #[cfg(feature = "zeroize")]
+        t.zeroize();
+        Ok(())
+    }
+
+    /// extract followed by expand for specific HKDF invocation, for callers
+    /// that only need a single derived key and do not need to reuse the
+    /// intermediate PRK across multiple `info` strings.
+    pub fn derive(&self, salt: Salt, ikm: &[u8], info: &[u8], out: &mut Vec<u8>) -> Result<(), ExpandError> {
+        let mut prk = Vec::new();
+        self.extract(salt, ikm, &mut prk);
+        let prk = Prk::new(prk);
+        self.expand(&prk, info, out)
+    }
+
+    /// expand for specific HKDF invocation, returning a fixed-size array
+    /// instead of requiring a pre-sized output buffer.
+    pub fn expand_array<const N: usize>(&self, prk: &[u8], info: &[u8]) -> Result<[u8; N], ExpandError> {
+        let mut out = vec![0; N];
+        let result = self.expand(prk, info, &mut out).map(|_| {
+            let mut okm = [0; N];
+            okm.copy_from_slice(&out);
+            okm
+        });
+        #[cfg(feature = "zeroize")]
+        out.zeroize();
+        result
+    }
+
+    /// extract followed by expand for specific HKDF invocation, returning a
+    /// fixed-size array instead of requiring a pre-sized output buffer.
+    pub fn derive_array<const N: usize>(&self, salt: Salt, ikm: &[u8], info: &[u8]) -> Result<[u8; N], ExpandError> {
+        let mut out = vec![0; N];
+        let result = self.derive(salt, ikm, info, &mut out).map(|_| {
+            let mut okm = [0; N];
+            okm.copy_from_slice(&out);
+            okm
+        });
+        #[cfg(feature = "zeroize")]
+        out.zeroize();
+        result
     }
 }
 
-/// runs HKDF_Extract as specified in
-/// [RFC5869](https://tools.ietf.org/html/rfc5869).
-pub fn extract(seed: &[u8], secret: &[u8], out: &mut Vec<u8>) {
-    if out.len() != SHA512_OUTPUT_BYTES_LENGTH {
-        copy_into_cleared(&[0; SHA512_OUTPUT_BYTES_LENGTH], out);
+/// A streaming HKDF-Extract context for callers whose input keying material
+/// is scattered across several buffers rather than available as one
+/// contiguous slice. Generic over the underlying hash function `H`, defaulting
+/// to SHA-512.
+///
+/// # Examples
+///
+/// ```
+/// use hkdf_sha512::{HkdfExtract,Salt};
+/// use sha2::Sha512;
+///
+/// let mut ctx = HkdfExtract::<Sha512>::new(Salt::NonEmpty("some_salt".as_bytes()));
+/// ctx.input_ikm("some_".as_bytes());
+/// ctx.input_ikm("secret_info".as_bytes());
+/// let (prk, hkdf) = ctx.finalize();
+///
+/// let mut out = vec![0; 70];
+/// hkdf.expand(&prk, "some_info".as_bytes(), &mut out).unwrap();
+/// ```
+pub struct HkdfExtract<H: Digest + Clone + BlockSizeUser = Sha512> {
+    hmac: SimpleHmac<H>,
+}
+
+impl<H: Digest + Clone + BlockSizeUser> HkdfExtract<H> {
+    /// Creates a new extraction context keyed by `salt`.
+    pub fn new(salt: Salt) -> Self {
+        let salt = salt.as_bytes(<H as Digest>::output_size());
+        HkdfExtract {
+            hmac: <SimpleHmac<H> as Mac>::new_from_slice(&salt)
+                .expect("HMAC accepts a key of any length"),
+        }
+    }
+
+    /// Absorbs the next chunk of input keying material.
+    pub fn input_ikm(&mut self, data: &[u8]) {
+        self.hmac.update(data);
+    }
+
+    /// Finalizes the running MAC, returning the PRK together with an `Hkdf`
+    /// instance ready to `expand` it.
+    pub fn finalize(self) -> (Prk, Hkdf<H>) {
+        let prk = self.hmac.finalize().into_bytes().to_vec();
+        (Prk::new(prk), Hkdf { _hash: PhantomData })
     }
-    hkdf_extract(Sha512::new(), &seed, &secret, out)
+}
+
+/// runs HKDF_Extract as specified in
+/// [RFC5869](https://tools.ietf.org/html/rfc5869), using SHA-512.
+pub fn extract(salt: Salt, secret: &[u8], out: &mut Vec<u8>) {
+    Hkdf::<Sha512>::new().extract(salt, secret, out)
 }
 
 /// runs HKDF_Expand as specified in
-/// [RFC5869](https://tools.ietf.org/html/rfc5869). The value of `prk`
-/// should be uniformly sampled bytes
-pub fn expand(prk: &[u8], info: &[u8], out: &mut Vec<u8>) {
-    hkdf_expand(Sha512::new(), &prk, &info, out)
+/// [RFC5869](https://tools.ietf.org/html/rfc5869), using SHA-512. The value
+/// of `prk` should be uniformly sampled bytes, typically the output of
+/// `extract`.
+///
+/// Returns `ExpandError::InvalidPrkLength` if `prk` is shorter than
+/// `HashLen` (64) bytes, and `ExpandError::InvalidLength` if `out` requests
+/// more than `255 * HashLen` bytes, as required by RFC5869.
+pub fn expand(prk: &[u8], info: &[u8], out: &mut Vec<u8>) -> Result<(), ExpandError> {
+    Hkdf::<Sha512>::new().expand(prk, info, out)
+}
+
+/// runs HKDF_Expand as specified in
+/// [RFC5869](https://tools.ietf.org/html/rfc5869), using SHA-512, returning
+/// a fixed-size `[u8; N]` rather than requiring the caller to pre-size a
+/// `Vec`.
+pub fn expand_array<const N: usize>(prk: &[u8], info: &[u8]) -> Result<[u8; N], ExpandError> {
+    Hkdf::<Sha512>::new().expand_array(prk, info)
+}
+
+/// runs HKDF_Extract followed immediately by HKDF_Expand as specified in
+/// [RFC5869](https://tools.ietf.org/html/rfc5869), using SHA-512, for
+/// callers that only need to derive a single key from `ikm` and do not need
+/// to reuse the intermediate PRK across multiple `info` strings.
+pub fn extract_and_expand(salt: Salt, ikm: &[u8], info: &[u8], out: &mut Vec<u8>) -> Result<(), ExpandError> {
+    Hkdf::<Sha512>::new().derive(salt, ikm, info, out)
+}
+
+/// runs HKDF_Extract followed immediately by HKDF_Expand as specified in
+/// [RFC5869](https://tools.ietf.org/html/rfc5869), using SHA-512, returning
+/// a fixed-size `[u8; N]` rather than requiring the caller to pre-size a
+/// `Vec`.
+pub fn derive_array<const N: usize>(salt: Salt, ikm: &[u8], info: &[u8]) -> Result<[u8; N], ExpandError> {
+    Hkdf::<Sha512>::new().derive_array(salt, ikm, info)
+}
+
+/// Checks that `prk` is long enough to have been produced by `extract`.
+fn check_prk_length<H: Digest>(prk: &[u8]) -> Result<(), InvalidPrkLength> {
+    if prk.len() < <H as Digest>::output_size() {
+        return Err(InvalidPrkLength);
+    }
+    Ok(())
 }
 
 /// Moves the contents of `src` into the provided output buffer `dst`. Clears
@@ -96,4 +414,101 @@ pub fn expand(prk: &[u8], info: &[u8], out: &mut Vec<u8>) {
 fn copy_into_cleared(src: &[u8], dst: &mut Vec<u8>) {
     dst.clear();
     dst.extend_from_slice(src)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Known-answer tests from RFC5869, Appendix A
+    // (https://tools.ietf.org/html/rfc5869#appendix-A). The RFC only
+    // publishes SHA-256 vectors, but extract/expand are otherwise identical
+    // for every `H`, so these also cover HkdfSha512's T(i)/counter logic.
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rfc5869_test_case_1() {
+        let ikm = hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b");
+        let salt = hex("000102030405060708090a0b0c");
+        let info = hex("f0f1f2f3f4f5f6f7f8f9");
+
+        let hkdf = HkdfSha256::default();
+        let mut prk = Vec::new();
+        hkdf.extract(Salt::NonEmpty(&salt), &ikm, &mut prk);
+        assert_eq!(
+            prk,
+            hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+        );
+
+        let mut okm = vec![0; 42];
+        hkdf.expand(&prk, &info, &mut okm).unwrap();
+        assert_eq!(
+            okm,
+            hex("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865")
+        );
+    }
+
+    #[test]
+    fn rfc5869_test_case_2() {
+        let ikm = hex(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f\
+             202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f\
+             404142434445464748494a4b4c4d4e4f",
+        );
+        let salt = hex(
+            "606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f\
+             808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f\
+             a0a1a2a3a4a5a6a7a8a9aaabacadaeaf",
+        );
+        let info = hex(
+            "b0b1b2b3b4b5b6b7b8b9babbbcbdbebfc0c1c2c3c4c5c6c7c8c9cacbcccdcecf\
+             d0d1d2d3d4d5d6d7d8d9dadbdcdddedfe0e1e2e3e4e5e6e7e8e9eaebecedeeef\
+             f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff",
+        );
+
+        let hkdf = HkdfSha256::default();
+        let mut prk = Vec::new();
+        hkdf.extract(Salt::NonEmpty(&salt), &ikm, &mut prk);
+        assert_eq!(
+            prk,
+            hex("06a6b88c5853361a06104c9ceb35b45cef760014904671014a193f40c15fc244")
+        );
+
+        let mut okm = vec![0; 82];
+        hkdf.expand(&prk, &info, &mut okm).unwrap();
+        assert_eq!(
+            okm,
+            hex(
+                "b11e398dc80327a1c8e7f78c596a49344f012eda2d4efad8a050cc4c19afa97\
+                 c59045a99cac7827271cb41c65e590e09da3275600c2f09b8367793a9aca3db\
+                 71cc30c58179ec3e87c14c01d5c1f3434f1d87"
+            )
+        );
+    }
+
+    #[test]
+    fn rfc5869_test_case_3() {
+        let ikm = hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b");
+
+        let hkdf = HkdfSha256::default();
+        let mut prk = Vec::new();
+        hkdf.extract(Salt::None, &ikm, &mut prk);
+        assert_eq!(
+            prk,
+            hex("19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04")
+        );
+
+        let mut okm = vec![0; 42];
+        hkdf.expand(&prk, &[], &mut okm).unwrap();
+        assert_eq!(
+            okm,
+            hex("8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8")
+        );
+    }
+}